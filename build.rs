@@ -0,0 +1,41 @@
+fn main() {
+    #[cfg(feature = "protobuf-codec")]
+    {
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        protobuf_codegen::Codegen::new()
+            .pure()
+            .out_dir(&out_dir)
+            .include("proto")
+            .input("proto/profile.proto")
+            .run()
+            .expect("compile proto/profile.proto with protobuf-codegen");
+        strip_inner_attributes(&format!("{}/profile.rs", out_dir));
+    }
+
+    #[cfg(feature = "prost-codec")]
+    {
+        // prost-build shells out to `protoc`; use the vendored binary so the
+        // build doesn't depend on one being installed on the host.
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        prost_build::compile_protos(&["proto/profile.proto"], &["proto/"])
+            .expect("compile proto/profile.proto with prost-build");
+    }
+}
+
+// `src/protos.rs` pulls the generated file in with `include!`, but rustc only
+// accepts inner attributes (`#![...]`) and inner doc comments (`//!`) written
+// literally at the start of a file, not ones spliced in through a macro. Strip
+// the header protobuf-codegen emits so the include keeps working.
+#[cfg(feature = "protobuf-codec")]
+fn strip_inner_attributes(path: &str) {
+    let contents = std::fs::read_to_string(path).expect("read generated profile.rs");
+    let filtered: String = contents
+        .lines()
+        .filter(|line| {
+            let trimmed = line.trim_start();
+            !trimmed.starts_with("#![") && !trimmed.starts_with("//!")
+        })
+        .map(|line| format!("{}\n", line))
+        .collect();
+    std::fs::write(path, filtered).expect("write stripped profile.rs");
+}
@@ -0,0 +1,47 @@
+//! Command-line interface for ckb-vm-pprof-converter.
+
+use clap::{Parser, ValueEnum};
+
+/// Which output(s) to produce. Rejected up front by clap if unrecognized,
+/// rather than silently matching nothing in `main`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "lower")]
+pub enum OutputFormat {
+    Pprof,
+    Flamegraph,
+    Both,
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "Converts ckb-vm folded-stack profiles into pprof/flamegraph output")]
+pub struct Cli {
+    /// Input file to read folded stacks from (`frameA; frameB; ... <cycles>`
+    /// per line), or `-` to read from stdin.
+    #[arg(long, default_value = "-")]
+    pub input: String,
+
+    /// Path to write the pprof profile to. When `--format` includes the
+    /// flame graph, the SVG is written next to it (`.pprof` replaced with
+    /// `.svg`, or `.svg` appended if `output` has no such suffix).
+    #[arg(long, default_value = "output.pprof")]
+    pub output: String,
+
+    /// CKB-VM clock frequency in Hz, used to convert cycles to nanoseconds.
+    #[arg(long, default_value_t = 1_000_000_000)]
+    pub frequency: u64,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Pprof)]
+    pub format: OutputFormat,
+
+    /// off|rust|cpp|auto
+    #[arg(long, default_value = "auto")]
+    pub demangle: String,
+
+    /// Path to a TOML/JSON symbol post-processing config.
+    #[arg(long)]
+    pub post_process_config: Option<String>,
+
+    /// Path to the profiled RISC-V ELF binary, used to emit a Mapping entry.
+    #[arg(long)]
+    pub binary: Option<String>,
+}
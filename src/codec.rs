@@ -0,0 +1,95 @@
+//! Wire-format abstraction: the same logical `Profile` built in `main` can be
+//! serialized through either rust-protobuf (`protobuf-codec`, the default)
+//! or prost (`prost-codec`), following pprof-rs's move to prost (PR
+//! #166/#175). Only the final encode step differs between the two; `profile`
+//! resolves to whichever codec's generated types are active so the rest of
+//! the crate doesn't need to know which one it's talking to.
+
+#[cfg(feature = "protobuf-codec")]
+pub(crate) use crate::protos as protobuf_profile;
+
+#[cfg(feature = "prost-codec")]
+// Only reachable as `profile` when `protobuf-codec` is off; with both features
+// on (the default) it's still built so `protobuf_and_prost_encode_identically`
+// below can compare the two, which is otherwise dead code.
+#[allow(dead_code)]
+pub(crate) mod prost_profile {
+    include!(concat!(env!("OUT_DIR"), "/perftools.profiles.rs"));
+}
+
+#[cfg(feature = "protobuf-codec")]
+pub(crate) use protobuf_profile as profile;
+#[cfg(all(feature = "prost-codec", not(feature = "protobuf-codec")))]
+pub(crate) use prost_profile as profile;
+
+/// Encodes a built `Profile` into the pprof wire format.
+pub trait Encode {
+    fn encode_profile(self) -> Vec<u8>;
+}
+
+/// Builds a `ValueType`, hiding the one place the two codecs disagree on
+/// field naming: rust-protobuf renames the `type` proto field to `type_`
+/// (a reserved word), while prost keeps it as the raw identifier `r#type`.
+#[cfg(feature = "protobuf-codec")]
+pub(crate) fn value_type(type_: i64, unit: i64) -> profile::ValueType {
+    profile::ValueType {
+        type_,
+        unit,
+        ..Default::default()
+    }
+}
+#[cfg(all(feature = "prost-codec", not(feature = "protobuf-codec")))]
+pub(crate) fn value_type(type_: i64, unit: i64) -> profile::ValueType {
+    profile::ValueType { r#type: type_, unit }
+}
+
+/// Wraps a `ValueType` for `Profile::period_type`: rust-protobuf represents an
+/// optional message as `MessageField<T>` (needing `Option<T>::into()`), while
+/// prost represents it as a plain `Option<T>` already (where the same
+/// `.into()` is a no-op clippy flags as useless).
+#[cfg(feature = "protobuf-codec")]
+pub(crate) fn period_type(v: profile::ValueType) -> ::protobuf::MessageField<profile::ValueType> {
+    Some(v).into()
+}
+#[cfg(all(feature = "prost-codec", not(feature = "protobuf-codec")))]
+pub(crate) fn period_type(v: profile::ValueType) -> Option<profile::ValueType> {
+    Some(v)
+}
+
+#[cfg(feature = "protobuf-codec")]
+impl Encode for protobuf_profile::Profile {
+    fn encode_profile(self) -> Vec<u8> {
+        use protobuf::Message;
+        self.write_to_bytes().expect("protobuf serialization")
+    }
+}
+
+#[cfg(feature = "prost-codec")]
+impl Encode for prost_profile::Profile {
+    fn encode_profile(self) -> Vec<u8> {
+        prost::Message::encode_to_vec(&self)
+    }
+}
+
+#[cfg(all(test, feature = "protobuf-codec", feature = "prost-codec"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protobuf_and_prost_encode_identically() {
+        let protobuf_profile = protobuf_profile::Profile {
+            string_table: vec!["".to_string(), "main".to_string()],
+            period: 1,
+            ..Default::default()
+        };
+        let prost_profile = prost_profile::Profile {
+            string_table: vec!["".to_string(), "main".to_string()],
+            period: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            protobuf_profile.encode_profile(),
+            prost_profile.encode_profile()
+        );
+    }
+}
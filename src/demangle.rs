@@ -0,0 +1,86 @@
+//! Rust/C++ symbol demangling for display purposes. The mangled form parsed
+//! from the input is always kept around separately (see `Symbol::mangled`) so it
+//! can still be used as `Function::system_name` for cross-referencing, even
+//! after `name` is replaced with a human-readable demangled string.
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemangleMode {
+    Off,
+    Rust,
+    Cpp,
+    Auto,
+}
+
+impl FromStr for DemangleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(DemangleMode::Off),
+            "rust" => Ok(DemangleMode::Rust),
+            "cpp" => Ok(DemangleMode::Cpp),
+            "auto" => Ok(DemangleMode::Auto),
+            _ => Err(format!("unknown demangle mode: {}", s)),
+        }
+    }
+}
+
+/// Demangles `name` according to `mode`, returning `name` unchanged if it
+/// isn't mangled (or demangling fails).
+pub fn demangle(name: &str, mode: DemangleMode) -> String {
+    match mode {
+        DemangleMode::Off => name.to_string(),
+        DemangleMode::Rust => demangle_rust(name).unwrap_or_else(|| name.to_string()),
+        DemangleMode::Cpp => demangle_cpp(name).unwrap_or_else(|| name.to_string()),
+        DemangleMode::Auto => {
+            if name.starts_with("_ZN") || name.starts_with("_R") {
+                demangle_rust(name)
+                    .or_else(|| demangle_cpp(name))
+                    .unwrap_or_else(|| name.to_string())
+            } else {
+                demangle_cpp(name).unwrap_or_else(|| name.to_string())
+            }
+        }
+    }
+}
+
+fn demangle_rust(name: &str) -> Option<String> {
+    rustc_demangle::try_demangle(name)
+        .ok()
+        .map(|demangled| format!("{:#}", demangled))
+}
+
+fn demangle_cpp(name: &str) -> Option<String> {
+    cpp_demangle::Symbol::new(name)
+        .ok()
+        .and_then(|symbol| symbol.demangle(&cpp_demangle::DemangleOptions::default()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_detects_and_demangles_rust_symbols() {
+        assert_eq!(demangle("_ZN3foo3barE", DemangleMode::Auto), "foo::bar");
+        assert_eq!(demangle("_ZN3foo3barE", DemangleMode::Rust), "foo::bar");
+    }
+
+    #[test]
+    fn auto_detects_and_demangles_cpp_symbols() {
+        assert_eq!(demangle("_Z3foov", DemangleMode::Auto), "foo()");
+        assert_eq!(demangle("_Z3foov", DemangleMode::Cpp), "foo()");
+    }
+
+    #[test]
+    fn off_mode_leaves_names_untouched() {
+        assert_eq!(demangle("_ZN3foo3barE", DemangleMode::Off), "_ZN3foo3barE");
+    }
+
+    #[test]
+    fn unmangled_names_pass_through_unchanged() {
+        assert_eq!(demangle("plain_function", DemangleMode::Auto), "plain_function");
+    }
+}
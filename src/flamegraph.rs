@@ -0,0 +1,90 @@
+//! Renders the parsed stacks directly to an interactive flame graph SVG,
+//! reusing the folded-stack format the input parser already understands
+//! (`frameA; frameB; ... <cycles>`) as inferno's input. Weighting stays in
+//! cycles rather than sample counts, matching the rest of this tool.
+
+use crate::Frame;
+use inferno::flamegraph::{from_lines, Options};
+use std::io::BufWriter;
+
+pub fn write(frames: &[Frame], output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let folded = folded_lines(frames);
+
+    let mut options = Options::default();
+    options.count_name = "cycles".to_string();
+    let file = std::fs::File::create(output_path)?;
+    let mut writer = BufWriter::new(file);
+    from_lines(
+        &mut options,
+        folded.iter().map(|line| line.as_str()),
+        &mut writer,
+    )?;
+    Ok(())
+}
+
+fn folded_lines(frames: &[Frame]) -> Vec<String> {
+    frames
+        .iter()
+        .map(|frame| {
+            // `frame.stack` is leaf-first (reversed in `parse_frame` to match
+            // pprof's `Sample.location_id` convention); inferno expects the
+            // opposite, root-first folded-stack order, so walk it back to front.
+            let stack = frame
+                .stack
+                .iter()
+                .rev()
+                .map(|symbol| match &symbol.file {
+                    Some(file) => format!("{}:{}", file, symbol.name()),
+                    None => symbol.name(),
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            format!("{} {}", stack, frame.cycles)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Symbol;
+
+    fn symbol(name: &str, file: Option<&str>) -> Symbol {
+        Symbol {
+            name: Some(name.to_string()),
+            file: file.map(|f| f.to_string()),
+            mangled: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn folded_lines_restore_root_first_order() {
+        // Mirrors `parse_frame`: stdin's `"main; foo; bar 100"` is parsed and
+        // then reversed to leaf-first `[bar, foo, main]`.
+        let frames = vec![Frame {
+            stack: vec![
+                symbol("bar", Some("bar.rs")),
+                symbol("foo", Some("foo.rs")),
+                symbol("main", Some("main.rs")),
+            ],
+            cycles: 100,
+        }];
+        let lines = folded_lines(&frames);
+        assert_eq!(
+            lines,
+            vec!["main.rs:main; foo.rs:foo; bar.rs:bar 100".to_string()]
+        );
+    }
+
+    #[test]
+    fn folded_lines_omit_file_prefix_when_unknown() {
+        // The tool's own example input (`"main; foo; bar 100"`) has no `file:`
+        // prefix at all, so the common case shouldn't render `<Unknown>:`.
+        let frames = vec![Frame {
+            stack: vec![symbol("bar", None), symbol("foo", None), symbol("main", None)],
+            cycles: 100,
+        }];
+        let lines = folded_lines(&frames);
+        assert_eq!(lines, vec!["main; foo; bar 100".to_string()]);
+    }
+}
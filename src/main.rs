@@ -1,14 +1,40 @@
+mod cli;
+mod codec;
+mod demangle;
+mod flamegraph;
+mod post_process;
+// Generated code; see `src/protos.rs` for how it gets here. The usual
+// `#![allow(...)]` lines protobuf-codegen emits can't survive being spliced in
+// via `include!` (build.rs strips them), so the allows live here instead.
+#[cfg(feature = "protobuf-codec")]
+#[allow(
+    dead_code,
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    unused_mut,
+    clippy::all
+)]
 mod protos;
 
-use crate::protos::profile;
-use protobuf::Message;
-use std::collections::{HashMap, HashSet};
+use crate::cli::{Cli, OutputFormat};
+use crate::codec::{period_type, profile, value_type, Encode};
+use crate::demangle::DemangleMode;
+use clap::Parser;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::BufRead;
 use std::str::FromStr;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Symbol {
     pub name: Option<String>,
     pub file: Option<String>,
+    /// The symbol name exactly as parsed from the input, before demangling or
+    /// `normalize_function_name` are applied. Kept around so it can still be
+    /// used as `Function::system_name` once `name` becomes a demangled,
+    /// human-readable display string.
+    pub mangled: Option<String>,
 }
 
 impl Symbol {
@@ -19,10 +45,14 @@ impl Symbol {
     pub fn file(&self) -> String {
         self.file.clone().unwrap_or("<Unknown>".to_owned())
     }
+
+    pub fn mangled(&self) -> String {
+        self.mangled.clone().unwrap_or("<Unknown>".to_owned())
+    }
 }
 
-struct Frame {
-    stack: Vec<Symbol>,
+pub(crate) struct Frame {
+    pub(crate) stack: Vec<Symbol>,
     cycles: u64,
 }
 
@@ -31,80 +61,190 @@ const COUNT: &str = "count";
 const CPU: &str = "cpu";
 const NANOSECONDS: &str = "nanoseconds";
 
-// TODO: make this a CLI argument, right now it's set as 1Ghz, meaning
-// 1 CKB cycle takes 1 nanosecond to run.
-const FREQUENCY: u64 = 1_000_000_000;
+/// Assigns string table indices as strings are first seen, instead of
+/// requiring every string to be known upfront. This is what lets `main`
+/// build the pprof tables line by line rather than over a fully
+/// materialized `Vec<Frame>`.
+struct StringInterner {
+    table: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl StringInterner {
+    fn new() -> Self {
+        // string table's first element must be an empty string
+        StringInterner {
+            table: vec!["".to_owned()],
+            indices: HashMap::from([("".to_owned(), 0)]),
+        }
+    }
+
+    fn intern(&mut self, s: &str) -> i64 {
+        if let Some(index) = self.indices.get(s) {
+            return *index as i64;
+        }
+        let index = self.table.len();
+        self.table.push(s.to_owned());
+        self.indices.insert(s.to_owned(), index);
+        index as i64
+    }
+
+    fn into_table(self) -> Vec<String> {
+        self.table
+    }
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut frames = Vec::new();
-
-    for line in std::io::stdin().lines() {
-        let line = line?;
-        let i = line.rfind(" ").expect("no cycles available!");
-
-        let mut stack: Vec<Symbol> = line[0..i]
-            .split("; ")
-            .map(|s| match s.find(":") {
-                Some(j) => Symbol {
-                    file: Some(s[0..j].to_string()),
-                    name: Some(normalize_function_name(&s[j + 1..s.len()])),
-                },
-                None => Symbol {
-                    name: Some(normalize_function_name(s)),
-                    file: None,
-                },
-            })
-            .collect();
-        stack.reverse();
-        let cycles = u64::from_str(&line[i + 1..line.len()]).expect("invalid cycle");
-
-        frames.push(Frame { stack, cycles });
-    }
-
-    let mut dedup_str: HashSet<String> = HashSet::new();
-    for Frame { stack, .. } in &frames {
-        for symbol in stack {
-            dedup_str.insert(symbol.name());
-            dedup_str.insert(symbol.file());
+    let cli = Cli::parse();
+    if cli.frequency == 0 {
+        return Err("--frequency must be non-zero".into());
+    }
+    let demangle_mode: DemangleMode = cli.demangle.parse().expect("invalid --demangle mode");
+    let compiled_rules = match &cli.post_process_config {
+        Some(path) => post_process::compile(&post_process::load_config(path)?.rules)?,
+        None => post_process::compile(&[])?,
+    };
+    let needs_pprof = matches!(cli.format, OutputFormat::Pprof | OutputFormat::Both);
+    let needs_flamegraph = matches!(cli.format, OutputFormat::Flamegraph | OutputFormat::Both);
+
+    let mapping_info = cli
+        .binary
+        .as_ref()
+        .map(|path| -> Result<_, Box<dyn std::error::Error>> {
+            let binary = std::fs::read(path)?;
+            Ok((path.clone(), compute_build_id(&binary)))
+        })
+        .transpose()?;
+    let mapping_id = if mapping_info.is_some() { 1 } else { 0 };
+
+    let mut tables = PprofTables::new();
+    let mut flamegraph_frames = vec![];
+
+    let input = open_input(&cli.input)?;
+    for line in input.lines() {
+        let mut frame = parse_frame(&line?, demangle_mode);
+        post_process::apply(&mut frame.stack, &compiled_rules);
+
+        if needs_pprof {
+            tables.add_frame(&frame, mapping_id, cli.frequency);
+        }
+        if needs_flamegraph {
+            flamegraph_frames.push(frame);
         }
     }
 
-    dedup_str.insert(SAMPLES.into());
-    dedup_str.insert(COUNT.into());
-    dedup_str.insert(CPU.into());
-    dedup_str.insert(NANOSECONDS.into());
+    if needs_pprof {
+        let samples_value = value_type(tables.interner.intern(SAMPLES), tables.interner.intern(COUNT));
+        let time_value = value_type(tables.interner.intern(CPU), tables.interner.intern(NANOSECONDS));
+        // Built twice rather than cloned: `ValueType` is `Copy` under
+        // prost-codec, so cloning it there trips clippy's `clone_on_copy`.
+        // `interner.intern` is idempotent, so this just looks up the same
+        // indices again.
+        let period_type_value = value_type(tables.interner.intern(CPU), tables.interner.intern(NANOSECONDS));
+        let mapping_tbl = build_mapping_table(&mut tables.interner, &mapping_info);
+        let profile = profile::Profile {
+            sample_type: vec![samples_value, time_value],
+            sample: tables.samples,
+            mapping: mapping_tbl,
+            string_table: tables.interner.into_table(),
+            function: tables.fn_tbl,
+            location: tables.loc_tbl,
+            period_type: period_type(period_type_value),
+            period: 1_000_000_000 / cli.frequency as i64,
+            ..Default::default()
+        };
+        let data = profile.encode_profile();
+        std::fs::write(&cli.output, data)?;
+    }
+
+    if needs_flamegraph {
+        flamegraph::write(&flamegraph_frames, &derive_svg_path(&cli.output))?;
+    }
+
+    Ok(())
+}
+
+fn open_input(path: &str) -> Result<Box<dyn BufRead>, Box<dyn std::error::Error>> {
+    if path == "-" {
+        Ok(Box::new(std::io::stdin().lock()))
+    } else {
+        Ok(Box::new(std::io::BufReader::new(std::fs::File::open(path)?)))
+    }
+}
+
+fn derive_svg_path(output: &str) -> String {
+    match output.strip_suffix(".pprof") {
+        Some(stem) => format!("{}.svg", stem),
+        None => format!("{}.svg", output),
+    }
+}
+
+fn parse_frame(line: &str, demangle_mode: DemangleMode) -> Frame {
+    let i = line.rfind(" ").expect("no cycles available!");
 
-    // string table's first element must be an empty string
-    let mut str_tbl = vec!["".to_owned()];
-    str_tbl.extend(dedup_str.into_iter());
+    let mut stack: Vec<Symbol> = line[0..i]
+        .split("; ")
+        .map(|s| {
+            let (file, mangled) = match s.find(":") {
+                Some(j) => (Some(s[0..j].to_string()), s[j + 1..s.len()].to_string()),
+                None => (None, s.to_string()),
+            };
+            let demangled = demangle::demangle(&mangled, demangle_mode);
+            Symbol {
+                file,
+                name: Some(normalize_function_name(&demangled)),
+                mangled: Some(mangled),
+            }
+        })
+        .collect();
+    stack.reverse();
+    let cycles = u64::from_str(&line[i + 1..line.len()]).expect("invalid cycle");
 
-    let mut strings = HashMap::new();
-    for (index, name) in str_tbl.iter().enumerate() {
-        strings.insert(name.clone(), index);
+    Frame { stack, cycles }
+}
+
+/// Accumulates the pprof function/location/sample tables across frames, plus
+/// the string interner and function-id cache they share.
+struct PprofTables {
+    interner: StringInterner,
+    functions: HashMap<String, u64>,
+    fn_tbl: Vec<profile::Function>,
+    loc_tbl: Vec<profile::Location>,
+    samples: Vec<profile::Sample>,
+}
+
+impl PprofTables {
+    fn new() -> Self {
+        PprofTables {
+            interner: StringInterner::new(),
+            functions: HashMap::new(),
+            fn_tbl: vec![],
+            loc_tbl: vec![],
+            samples: vec![],
+        }
     }
 
-    let mut samples = vec![];
-    let mut loc_tbl = vec![];
-    let mut fn_tbl = vec![];
-    let mut functions = HashMap::new();
-    for Frame { stack, cycles } in &frames {
+    fn add_frame(&mut self, frame: &Frame, mapping_id: u64, frequency: u64) {
         let mut locs = vec![];
-        for symbol in stack {
+        for symbol in &frame.stack {
             let name = symbol.name();
-            if let Some(loc_idx) = functions.get(&name) {
+            if let Some(loc_idx) = self.functions.get(&name) {
                 locs.push(*loc_idx);
                 continue;
             }
-            let function_id = fn_tbl.len() as u64 + 1;
+            let function_id = self.fn_tbl.len() as u64 + 1;
             let function = profile::Function {
                 id: function_id,
-                name: strings[&name] as i64,
-                // TODO: distinguish between C++ mangled & unmangled names
-                system_name: strings[&name] as i64,
-                filename: strings[&symbol.file()] as i64,
+                name: self.interner.intern(&name),
+                system_name: self.interner.intern(&symbol.mangled()),
+                filename: self.interner.intern(&symbol.file()),
                 ..Default::default()
             };
-            functions.insert(name, function_id);
+            self.functions.insert(name, function_id);
+            // `..Default::default()` fills rust-protobuf's `special_fields`;
+            // prost's `Line` has no such field, so every field is already set
+            // and clippy (correctly, for that codec) calls this needless.
+            #[allow(clippy::needless_update)]
             let line = profile::Line {
                 function_id,
                 line: 0,
@@ -112,52 +252,125 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             };
             let loc = profile::Location {
                 id: function_id,
-                line: vec![line].into(),
+                mapping_id,
+                line: vec![line],
                 ..Default::default()
             };
             // the fn_tbl has the same length with loc_tbl
-            fn_tbl.push(function);
-            loc_tbl.push(loc);
+            self.fn_tbl.push(function);
+            self.loc_tbl.push(loc);
             // current frame locations
             locs.push(function_id);
         }
+        // See the `Line` literal above: needed for rust-protobuf's
+        // `special_fields`, needless under prost-codec where every field of
+        // `Sample` is already set.
+        #[allow(clippy::needless_update)]
         let sample = profile::Sample {
             location_id: locs,
             value: vec![
-                *cycles as i64,
-                *cycles as i64 * 1_000_000_000 / FREQUENCY as i64,
+                frame.cycles as i64,
+                frame.cycles as i64 * 1_000_000_000 / frequency as i64,
             ],
-            label: vec![].into(),
+            label: vec![],
             ..Default::default()
         };
-        samples.push(sample);
+        self.samples.push(sample);
     }
-    let samples_value = profile::ValueType {
-        field_type: strings[SAMPLES] as i64,
-        unit: strings[COUNT] as i64,
-        ..Default::default()
-    };
-    let time_value = profile::ValueType {
-        field_type: strings[CPU] as i64,
-        unit: strings[NANOSECONDS] as i64,
-        ..Default::default()
-    };
-    let profile = profile::Profile {
-        sample_type: vec![samples_value, time_value.clone()].into(),
-        sample: samples.into(),
-        string_table: str_tbl.into(),
-        function: fn_tbl.into(),
-        location: loc_tbl.into(),
-        period_type: Some(time_value).into(),
-        period: 1_000_000_000 / FREQUENCY as i64,
-        ..Default::default()
-    };
-    let data = profile.write_to_bytes().expect("protobuf serialization");
-    std::fs::write("output.pprof", data)?;
-
-    Ok(())
 }
 
 fn normalize_function_name(name: &str) -> String {
     name.replace("<", "{").replace(">", "}").to_string()
 }
+
+fn compute_build_id(binary: &[u8]) -> String {
+    hex::encode(Sha256::digest(binary))
+}
+
+fn build_mapping_table(
+    interner: &mut StringInterner,
+    mapping_info: &Option<(String, String)>,
+) -> Vec<profile::Mapping> {
+    match mapping_info {
+        Some((path, build_id)) => vec![profile::Mapping {
+            id: 1,
+            filename: interner.intern(path),
+            build_id: interner.intern(build_id),
+            ..Default::default()
+        }],
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compute_build_id_hashes_binary_contents() {
+        let digest = compute_build_id(b"binary contents");
+        assert_eq!(digest, hex::encode(Sha256::digest(b"binary contents")));
+        assert_eq!(digest.len(), 64);
+    }
+
+    #[test]
+    fn frame_locations_get_mapping_id_when_binary_is_set() {
+        let frame = Frame {
+            stack: vec![Symbol {
+                name: Some("main".to_string()),
+                file: Some("main.rs".to_string()),
+                mangled: Some("main".to_string()),
+            }],
+            cycles: 42,
+        };
+        let mut tables = PprofTables::new();
+        tables.add_frame(&frame, 1, 1_000_000_000);
+        assert_eq!(tables.loc_tbl[0].mapping_id, 1);
+    }
+
+    #[test]
+    fn frequency_controls_cycle_to_nanosecond_conversion() {
+        let frame = Frame {
+            stack: vec![Symbol {
+                name: Some("main".to_string()),
+                file: Some("main.rs".to_string()),
+                mangled: Some("main".to_string()),
+            }],
+            cycles: 500,
+        };
+        let mut tables = PprofTables::new();
+        // 500 cycles at 500MHz take 1_000ns.
+        tables.add_frame(&frame, 0, 500_000_000);
+        assert_eq!(tables.samples[0].value, vec![500, 1_000]);
+    }
+
+    #[test]
+    fn open_input_streams_lines_from_a_file() {
+        let path = std::env::temp_dir().join(format!("ckb-vm-pprof-converter-test-{}.txt", std::process::id()));
+        std::fs::write(&path, "main; foo 10\nmain; bar 20\n").unwrap();
+        let input = open_input(path.to_str().unwrap()).unwrap();
+        let lines: Vec<String> = input.lines().map(|line| line.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines, vec!["main; foo 10".to_string(), "main; bar 20".to_string()]);
+    }
+
+    #[test]
+    fn parse_frame_demangles_name_but_keeps_mangled_for_system_name() {
+        let frame = parse_frame("main.rs:_ZN3foo3barE 100", DemangleMode::Auto);
+        let symbol = &frame.stack[0];
+        assert_eq!(symbol.name(), "foo::bar");
+        assert_eq!(symbol.mangled(), "_ZN3foo3barE");
+    }
+
+    #[test]
+    fn build_mapping_table_interns_path_and_build_id() {
+        let mut interner = StringInterner::new();
+        let mapping_info = Some(("/bin/contract".to_string(), "deadbeef".to_string()));
+        let mappings = build_mapping_table(&mut interner, &mapping_info);
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].id, 1);
+        let table = interner.into_table();
+        assert_eq!(table[mappings[0].filename as usize], "/bin/contract");
+        assert_eq!(table[mappings[0].build_id as usize], "deadbeef");
+    }
+}
@@ -0,0 +1,157 @@
+//! Post-processing pipeline applied to parsed stacks before the pprof
+//! string/function tables are built. Mirrors pprof-rs's `FramesPostProcessor`
+//! idea, but rules here are data-driven (loaded from a TOML/JSON config)
+//! instead of closures, since this tool has no embedding API to hand a
+//! closure through.
+
+use crate::Symbol;
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct PostProcessConfig {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    /// Rename symbols whose name matches `pattern`, replacing every match with
+    /// `replacement` (regex capture groups such as `$1` are supported, as in
+    /// `Regex::replace_all`).
+    Rename { pattern: String, replacement: String },
+    /// Drop stack entries whose symbol name matches `pattern`, splicing them
+    /// out of the stack so the caller/callee on either side become adjacent.
+    Drop { pattern: String },
+    /// Collapse consecutive stack entries that refer to the same symbol into
+    /// a single entry, useful for deep recursion in CKB contracts.
+    CollapseRecursion,
+}
+
+/// A `Rule` compiled into its executable form. Kept separate from the
+/// deserialized `Rule` so regexes are compiled once, not per frame.
+enum CompiledRule {
+    Rename(Regex, String),
+    Drop(Regex),
+    CollapseRecursion,
+}
+
+/// Rules compiled once up front, then applied to every frame as it streams
+/// in off the input.
+pub struct CompiledRules(Vec<CompiledRule>);
+
+pub fn load_config(path: &str) -> Result<PostProcessConfig, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    if path.ends_with(".json") {
+        Ok(serde_json::from_str(&content)?)
+    } else {
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+pub fn compile(rules: &[Rule]) -> Result<CompiledRules, Box<dyn std::error::Error>> {
+    rules
+        .iter()
+        .map(|rule| {
+            Ok(match rule {
+                Rule::Rename { pattern, replacement } => {
+                    CompiledRule::Rename(Regex::new(pattern)?, replacement.clone())
+                }
+                Rule::Drop { pattern } => CompiledRule::Drop(Regex::new(pattern)?),
+                Rule::CollapseRecursion => CompiledRule::CollapseRecursion,
+            })
+        })
+        .collect::<Result<_, Box<dyn std::error::Error>>>()
+        .map(CompiledRules)
+}
+
+/// Applies `rules` in declared order to a single frame's stack.
+pub fn apply(stack: &mut Vec<Symbol>, rules: &CompiledRules) {
+    for rule in &rules.0 {
+        apply_one(stack, rule);
+    }
+}
+
+fn apply_one(stack: &mut Vec<Symbol>, rule: &CompiledRule) {
+    match rule {
+        CompiledRule::Rename(re, replacement) => {
+            for symbol in stack.iter_mut() {
+                let name = symbol.name();
+                if re.is_match(&name) {
+                    symbol.name = Some(re.replace_all(&name, replacement.as_str()).into_owned());
+                }
+            }
+        }
+        CompiledRule::Drop(re) => {
+            stack.retain(|symbol| !re.is_match(&symbol.name()));
+        }
+        CompiledRule::CollapseRecursion => {
+            stack.dedup_by(|a, b| a.name() == b.name() && a.file() == b.file());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, file: &str) -> Symbol {
+        Symbol {
+            name: Some(name.to_string()),
+            file: Some(file.to_string()),
+            mangled: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn rename_replaces_matching_names() {
+        let rules = compile(&[Rule::Rename {
+            pattern: "^alloc::.*".to_string(),
+            replacement: "alloc".to_string(),
+        }])
+        .unwrap();
+        let mut stack = vec![symbol("alloc::alloc::exchange_malloc", "alloc.rs"), symbol("main", "main.rs")];
+        apply(&mut stack, &rules);
+        assert_eq!(stack[0].name(), "alloc");
+        assert_eq!(stack[1].name(), "main");
+    }
+
+    #[test]
+    fn drop_splices_out_matching_entries() {
+        let rules = compile(&[Rule::Drop {
+            pattern: "^__rust_".to_string(),
+        }])
+        .unwrap();
+        let mut stack = vec![
+            symbol("main", "main.rs"),
+            symbol("__rust_alloc", "alloc.rs"),
+            symbol("foo", "foo.rs"),
+        ];
+        apply(&mut stack, &rules);
+        let names: Vec<String> = stack.iter().map(Symbol::name).collect();
+        assert_eq!(names, vec!["main".to_string(), "foo".to_string()]);
+    }
+
+    #[test]
+    fn collapse_recursion_merges_consecutive_identical_frames() {
+        let rules = compile(&[Rule::CollapseRecursion]).unwrap();
+        let mut stack = vec![
+            symbol("recurse", "foo.rs"),
+            symbol("recurse", "foo.rs"),
+            symbol("recurse", "foo.rs"),
+            symbol("main", "main.rs"),
+        ];
+        apply(&mut stack, &rules);
+        let names: Vec<String> = stack.iter().map(Symbol::name).collect();
+        assert_eq!(names, vec!["recurse".to_string(), "main".to_string()]);
+    }
+
+    #[test]
+    fn collapse_recursion_keeps_same_named_symbols_from_different_files() {
+        let rules = compile(&[Rule::CollapseRecursion]).unwrap();
+        let mut stack = vec![symbol("handle", "contract_a.rs"), symbol("handle", "contract_b.rs")];
+        apply(&mut stack, &rules);
+        assert_eq!(stack.len(), 2);
+    }
+}
@@ -0,0 +1,4 @@
+//! Generated pprof protobuf types (rust-protobuf), built from
+//! `proto/profile.proto` by `build.rs` via `protobuf-codegen`.
+
+include!(concat!(env!("OUT_DIR"), "/profile.rs"));